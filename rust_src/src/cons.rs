@@ -4,15 +4,19 @@ use std::os::raw::c_char;
 use std::ptr;
 use std::mem;
 
-use lisp::{LispObject, LispType, XTYPE, XUNTAG, Qt, Qnil, LispSubr, EmacsInt, PvecType,
-           VectorLikeHeader, PSEUDOVECTOR_AREA_BITS, CHECK_TYPE};
+use lisp::{LispObject, LispType, XTYPE, XUNTAG, XINT, INTEGERP, Qt, Qnil, LispSubr, EmacsInt,
+           PvecType, VectorLikeHeader, PSEUDOVECTOR_AREA_BITS, CHECK_TYPE};
 
 extern "C" {
     static Qconsp: LispObject;
+    static Qconses: LispObject;
+    static Qlistp: LispObject;
+    static Qintegerp: LispObject;
+    static Qwholenump: LispObject;
     fn CHECK_IMPURE(obj: LispObject, ptr: *const libc::c_void);
+    fn make_number(n: EmacsInt) -> LispObject;
 }
 
-
 fn CONSP(x: LispObject) -> bool {
     XTYPE(x) == LispType::Lisp_Cons
 }
@@ -178,20 +182,43 @@ extern "C" {
     /// `XSETVECTOR`, `XSETSTRING`, `XSETFLOAT` and `XSETMISC`.
     fn make_lisp_ptr(ptr: *mut libc::c_void, ty: LispType) -> LispObject;
     fn lisp_align_malloc(nbytes: libc::size_t, ty: MemType) -> *mut libc::c_void;
+    fn lisp_align_free(block: *mut libc::c_void);
+    /// Walk every GC root (the C stack, obarray, specpdl, buffers,
+    /// ...) and mark everything reachable from them, calling back
+    /// into `cons_mark` for each live cons cell found. Not yet ported
+    /// to Rust; a collection is only safe to sweep after this runs.
+    fn mark_gc_roots();
     /// Free-list of Lisp_Cons structures.
     static mut cons_free_list: *mut LispConsChain;
     static mut consing_since_gc: EmacsInt;
     static mut total_free_conses: EmacsInt;
+    /// Number of live (marked) cons cells, as of the last sweep.
+    static mut total_conses: EmacsInt;
+    /// Chain of all currently allocated ConsBlocks, newest first.
+    static mut cons_block: *mut ConsBlock;
+    /// Index of the next unused cons cell in `cons_block`, or
+    /// `CONS_BLOCK_SIZE` if the current block is exhausted.
+    static mut cons_block_index: libc::c_int;
 }
 
-const CONS_BLOCK_SIZE: usize = 100;
-
 /// An unsigned integer type representing a fixed-length bit sequence,
 /// suitable for bool vector words, GC mark bits, etc.
 type bits_word = libc::size_t;
 
 const BITS_PER_BITS_WORD: usize = 8 * 8;
 
+/// Alignment of blocks returned by `lisp_align_malloc`, in bytes.
+const BLOCK_ALIGN: usize = 1 << 10;
+
+/// Number of usable bytes in one aligned block, after the `next`
+/// pointer's overhead has been carved out.
+const BLOCK_BYTES: usize = BLOCK_ALIGN - mem::size_of::<*mut libc::c_void>();
+
+/// Number of cons cells in a `ConsBlock`.
+///
+/// Sized so that each cell gets exactly one mark bit in `gcmarkbits`.
+const CONS_BLOCK_SIZE: usize = (BLOCK_BYTES * 8) / (mem::size_of::<LispCons>() * 8 + 1);
+
 /// The ConsBlock is used to store cons cells.
 ///
 /// We allocate new ConsBlock values when needed. Cons cells reclaimed
@@ -208,22 +235,55 @@ struct ConsBlock {
     next: *mut ConsBlock,
 }
 
+/// Round a cons cell's address down to the start of its containing
+/// `ConsBlock`.
+///
+/// Relies on `lisp_align_malloc` always returning `BLOCK_ALIGN`-aligned
+/// memory, so the block header can be recovered by masking off the
+/// low bits of any cons pointer within it.
+fn CONS_BLOCK(ptr: *mut LispCons) -> *mut ConsBlock {
+    (ptr as usize & !(BLOCK_ALIGN - 1)) as *mut ConsBlock
+}
+
+/// Index of a cons cell within its containing `ConsBlock`'s `conses` array.
+fn CONS_INDEX(ptr: *mut LispCons) -> usize {
+    (ptr as usize & (BLOCK_ALIGN - 1)) / mem::size_of::<LispCons>()
+}
+
 fn Fcons(car: LispObject, cdr: LispObject) -> LispObject {
     // MALLOC_BLOCK_INPUT; is a no-op.
 
-    let mut val: LispObject;
+    let val: LispObject;
 
-    val = 1;
     unsafe {
         if !cons_free_list.is_null() {
             // Use the current head of the free list for this cons
             // cell, and remove it from the free list.
             val = make_lisp_ptr(cons_free_list as *mut libc::c_void, LispType::Lisp_Cons);
             cons_free_list = (*cons_free_list).chain;
+            total_free_conses -= 1;
         } else {
-            // Otherwise, we need to malloc some meory.
-
-
+            // The free list is empty: carve the next cell out of the
+            // current block, allocating a fresh aligned block first
+            // if the current one is full.
+            if cons_block_index as usize == CONS_BLOCK_SIZE {
+                let new_block = lisp_align_malloc(mem::size_of::<ConsBlock>() as libc::size_t,
+                                                   MemType::MEM_TYPE_CONS) as
+                                 *mut ConsBlock;
+                (*new_block).next = cons_block;
+                cons_block = new_block;
+                cons_block_index = 0;
+                // lisp_align_malloc does not promise zeroed memory,
+                // and cons_marked_p/cons_mark/cons_unmark assume an
+                // unmarked bit means "unmarked", not "uninitialized".
+                let markbits = (*new_block).gcmarkbits.as_mut_ptr();
+                ptr::write_bytes(markbits, 0, (*new_block).gcmarkbits.len());
+            }
+
+            val = make_lisp_ptr(&mut (*cons_block).conses[cons_block_index as usize] as
+                                 *mut LispCons as *mut libc::c_void,
+                                 LispType::Lisp_Cons);
+            cons_block_index += 1;
         }
     }
 
@@ -233,14 +293,62 @@ fn Fcons(car: LispObject, cdr: LispObject) -> LispObject {
     // assert marked
 
     unsafe {
-        consing_since_gc += mem::size_of::<LispCons>() as i64;
-        total_free_conses += 1;
+        consing_since_gc += mem::size_of::<LispCons>() as EmacsInt;
         // cons_cells_consed++
     }
 
+    maybe_gc();
+
     val
 }
 
+/// Default value of `gc-cons-threshold`: the classic Emacs default of
+/// roughly 100,000 words worth of consing between collections.
+const DEFAULT_GC_CONS_THRESHOLD: EmacsInt = 100_000 * mem::size_of::<LispObject>() as EmacsInt;
+
+/// Default value of `gc-cons-percentage`.
+const DEFAULT_GC_CONS_PERCENTAGE: EmacsInt = 10;
+
+/// How many bytes may be consed since the last garbage collection
+/// before the next one is triggered automatically.
+///
+/// # Porting Notes
+///
+/// Tunable from elisp as `gc-cons-threshold`; `DEFVAR_INT` in C binds
+/// the Lisp variable directly to this storage.
+#[no_mangle]
+pub static mut gc_cons_threshold: EmacsInt = DEFAULT_GC_CONS_THRESHOLD;
+
+/// Additional collection condition, expressed as a percentage of the
+/// current cons heap size (live and free cells), so that heaps which have grown large don't
+/// collect needlessly often just because `gc_cons_threshold` is small.
+///
+/// # Porting Notes
+///
+/// Tunable from elisp as `gc-cons-percentage`.
+#[no_mangle]
+pub static mut gc_cons_percentage: EmacsInt = DEFAULT_GC_CONS_PERCENTAGE;
+
+/// Run a collection if enough consing has accumulated since the last
+/// one: past the absolute `gc_cons_threshold` *and* past
+/// `gc_cons_percentage` of the current cons heap size. Requiring both
+/// keeps a small heap from collecting constantly just because the
+/// percentage of a tiny heap is crossed immediately.
+///
+/// Called by the allocator every time `consing_since_gc` is bumped.
+fn maybe_gc() {
+    unsafe {
+        let heap_bytes = (total_conses + total_free_conses) * mem::size_of::<LispCons>() as EmacsInt;
+        let percentage_threshold = heap_bytes * gc_cons_percentage / 100;
+
+        if consing_since_gc > gc_cons_threshold && consing_since_gc > percentage_threshold {
+            mark_gc_roots();
+            sweep_cons();
+            consing_since_gc = 0;
+        }
+    }
+}
+
 lazy_static! {
     pub static ref Scons: LispSubr = LispSubr {
         header: VectorLikeHeader {
@@ -257,3 +365,445 @@ lazy_static! {
 (fn CAR CDR)\0".as_ptr()) as *const c_char,
     };
 }
+
+// Mark and sweep GC support for cons cells.
+//
+// Each ConsBlock carries one mark bit per cell in `gcmarkbits`. The
+// mark phase (stack/root scanning, done elsewhere) calls `cons_mark`
+// on every reachable cons; `sweep_cons` then reclaims everything left
+// unmarked.
+
+/// Return whether the cons cell at `ptr` is currently marked.
+fn cons_marked_p(ptr: *mut LispCons) -> bool {
+    unsafe {
+        let block = CONS_BLOCK(ptr);
+        let index = CONS_INDEX(ptr);
+        (*block).gcmarkbits[index / BITS_PER_BITS_WORD] & (1 << (index % BITS_PER_BITS_WORD)) != 0
+    }
+}
+
+/// Mark the cons cell OBJECT as reachable.
+///
+/// # Porting Notes
+///
+/// Exported like `Fsetcar`/`Fnreverse` so the root-marking phase
+/// (`mark_object` in C, still unported) can call into the Rust-owned
+/// mark bitmap for every live cons it discovers while walking the
+/// stack, obarray, specpdl, and the other GC roots.
+#[no_mangle]
+pub extern "C" fn cons_mark(object: LispObject) {
+    unsafe {
+        let ptr = XCONS(object);
+        let block = CONS_BLOCK(ptr);
+        let index = CONS_INDEX(ptr);
+        (*block).gcmarkbits[index / BITS_PER_BITS_WORD] |= 1 << (index % BITS_PER_BITS_WORD);
+    }
+}
+
+/// Clear the mark bit of the cons cell at `ptr`.
+fn cons_unmark(ptr: *mut LispCons) {
+    unsafe {
+        let block = CONS_BLOCK(ptr);
+        let index = CONS_INDEX(ptr);
+        (*block).gcmarkbits[index / BITS_PER_BITS_WORD] &= !(1 << (index % BITS_PER_BITS_WORD));
+    }
+}
+
+/// Return whether `ptr` points at a cons cell that is actually part of
+/// a known, currently-allocated `ConsBlock`.
+///
+/// # Porting Notes
+///
+/// Exported like `cons_mark` so the conservative stack scanner (still
+/// on the C side) can call this for every candidate word found on the
+/// C stack, which might just be an unrelated integer that happens to
+/// look like a valid address, rather than an actual live cons.
+#[no_mangle]
+pub extern "C" fn live_cons_p(ptr: *mut libc::c_void) -> bool {
+    let ptr = ptr as *mut LispCons;
+    unsafe {
+        let mut blk = cons_block;
+        while !blk.is_null() {
+            let start = (*blk).conses.as_ptr() as usize;
+            let end = start + CONS_BLOCK_SIZE * mem::size_of::<LispCons>();
+            let addr = ptr as usize;
+
+            if addr >= start && addr < end {
+                if (addr - start) % mem::size_of::<LispCons>() != 0 {
+                    return false;
+                }
+                if blk == cons_block && CONS_INDEX(ptr) >= cons_block_index as usize {
+                    return false;
+                }
+                return true;
+            }
+
+            blk = (*blk).next;
+        }
+
+        false
+    }
+}
+
+/// Sweep every `ConsBlock`, reclaiming unmarked cells onto
+/// `cons_free_list` and clearing the mark bits of live ones.
+///
+/// Blocks that turn out to be entirely free are returned to the
+/// system with `lisp_align_free` rather than kept around empty.
+pub fn sweep_cons() {
+    unsafe {
+        cons_free_list = ptr::null_mut();
+        total_conses = 0;
+        total_free_conses = 0;
+
+        let mut cblk = cons_block;
+        let mut prev: *mut *mut ConsBlock = &mut cons_block;
+
+        while !cblk.is_null() {
+            // Only the head of the chain (the block we are currently
+            // carving new cells from) may be partially filled.
+            let limit = if cblk == cons_block {
+                cons_block_index as usize
+            } else {
+                CONS_BLOCK_SIZE
+            };
+
+            let mut num_free = 0;
+            // Head of the free list before this block's cells were
+            // threaded onto it, restored if the block is freed below.
+            let free_list_before = cons_free_list;
+            for i in 0..limit {
+                let cons_ptr = &mut (*cblk).conses[i] as *mut LispCons;
+
+                if !cons_marked_p(cons_ptr) {
+                    let chain_ptr = cons_ptr as *mut LispConsChain;
+                    (*chain_ptr).chain = cons_free_list;
+                    cons_free_list = chain_ptr;
+                    total_free_conses += 1;
+                    num_free += 1;
+                } else {
+                    cons_unmark(cons_ptr);
+                    total_conses += 1;
+                }
+            }
+
+            let next = (*cblk).next;
+
+            if num_free == limit && limit == CONS_BLOCK_SIZE {
+                // The whole block is garbage: unlink and free it. If
+                // it was the head of the chain, force the next Fcons
+                // to allocate a fresh block rather than reuse the
+                // (already full) block that replaces it.
+                if cblk == cons_block {
+                    cons_block_index = CONS_BLOCK_SIZE as libc::c_int;
+                }
+                // Drop its cells from the free list again.
+                cons_free_list = free_list_before;
+                total_free_conses -= num_free as EmacsInt;
+                *prev = next;
+                lisp_align_free(cblk as *mut libc::c_void);
+            } else {
+                prev = &mut (*cblk).next;
+            }
+
+            cblk = next;
+        }
+    }
+}
+
+/// Run a full garbage collection and report accounting data about it,
+/// the way GNU Emacs's `garbage-collect` does.
+///
+/// The result is an alist with one entry per object type collected so
+/// far; as other allocators (strings, symbols, floats, vectors,
+/// buffers, ...) land in Rust, they each add their own `(SYMBOL
+/// USED . FREE)` entry here.
+fn Fgarbage_collect() -> LispObject {
+    unsafe {
+        mark_gc_roots();
+    }
+    sweep_cons();
+
+    unsafe {
+        consing_since_gc = 0;
+
+        let conses = Fcons(make_number(total_conses), make_number(total_free_conses));
+        let entries = Fcons(Qconses, conses);
+
+        Fcons(entries, Qnil)
+    }
+}
+
+lazy_static! {
+    pub static ref Sgarbagecollect: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fgarbage_collect as *const libc::c_void),
+        min_args: 0,
+        max_args: 0,
+        symbol_name: ("garbage-collect\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Reclaim storage for Lisp objects no longer needed.
+
+Returns an alist of statistics about objects that have been reclaimed,
+e.g. (conses USED . FREE).
+
+(fn)\0".as_ptr()) as *const c_char,
+    };
+}
+
+/// Return whether OBJECT is nil.
+fn NILP(object: LispObject) -> bool {
+    unsafe { object == Qnil }
+}
+
+/// Return whether OBJECT is a list: either a cons cell or nil.
+fn listp(object: LispObject) -> bool {
+    NILP(object) || CONSP(object)
+}
+
+/// Build a fresh list out of NARGS/ARGS, terminated by `Qnil`.
+///
+/// # Porting Notes
+///
+/// `args` points at a C array of `nargs` Lisp objects, the calling
+/// convention for `MANY` subrs (`&rest` arguments in elisp).
+#[no_mangle]
+pub extern "C" fn Flist(nargs: libc::ptrdiff_t, args: *mut LispObject) -> LispObject {
+    let mut val = unsafe { Qnil };
+
+    for i in (0..nargs).rev() {
+        val = Fcons(unsafe { *args.offset(i as isize) }, val);
+    }
+
+    val
+}
+
+lazy_static! {
+    pub static ref Slist: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Flist as *const libc::c_void),
+        min_args: 0,
+        max_args: -2,
+        symbol_name: ("list\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Return a newly created list with specified arguments as elements.
+Any number of arguments, even zero arguments, are allowed.
+
+(fn &rest OBJECTS)\0".as_ptr()) as *const c_char,
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn Fmake_list(length: LispObject, init: LispObject) -> LispObject {
+    unsafe {
+        CHECK_TYPE(INTEGERP(length) && XINT(length) >= 0, Qwholenump, length);
+    }
+
+    let mut val = unsafe { Qnil };
+    let n = unsafe { XINT(length) };
+
+    for _ in 0..n {
+        val = Fcons(init, val);
+    }
+
+    val
+}
+
+lazy_static! {
+    pub static ref Smake_list: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fmake_list as *const libc::c_void),
+        min_args: 2,
+        max_args: 2,
+        symbol_name: ("make-list\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Return a newly created list of length LENGTH, with each element being INIT.
+
+(fn LENGTH INIT)\0".as_ptr()) as *const c_char,
+    };
+}
+
+/// Take the cdr of LIST, N times, stopping early with `Qnil` if LIST
+/// runs out before then.
+#[no_mangle]
+pub extern "C" fn Fnthcdr(n: LispObject, list: LispObject) -> LispObject {
+    unsafe {
+        CHECK_TYPE(INTEGERP(n), Qintegerp, n);
+    }
+
+    let mut i = unsafe { XINT(n) };
+    let mut tail = list;
+
+    while i > 0 {
+        if !CONSP(tail) {
+            return unsafe { Qnil };
+        }
+        tail = unsafe { (*XCONS(tail)).cdr };
+        i -= 1;
+    }
+
+    tail
+}
+
+lazy_static! {
+    pub static ref Snthcdr: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fnthcdr as *const libc::c_void),
+        min_args: 2,
+        max_args: 2,
+        symbol_name: ("nthcdr\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Take cdr N times on LIST, return the result.
+
+(fn N LIST)\0".as_ptr()) as *const c_char,
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn Fnth(n: LispObject, list: LispObject) -> LispObject {
+    let tail = Fnthcdr(n, list);
+    if CONSP(tail) {
+        unsafe { (*XCONS(tail)).car }
+    } else {
+        unsafe { Qnil }
+    }
+}
+
+lazy_static! {
+    pub static ref Snth: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fnth as *const libc::c_void),
+        min_args: 2,
+        max_args: 2,
+        symbol_name: ("nth\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Return the Nth element of LIST.
+N counts from zero.  If LIST is not that long, nil is returned.
+
+(fn N LIST)\0".as_ptr()) as *const c_char,
+    };
+}
+
+/// Reverse LIST in place by rewriting each cell's cdr to point at its
+/// predecessor, and return the new head.
+#[no_mangle]
+pub extern "C" fn Fnreverse(list: LispObject) -> LispObject {
+    if NILP(list) {
+        return list;
+    }
+    unsafe {
+        CHECK_TYPE(CONSP(list), Qconsp, list);
+    }
+
+    let mut prev = unsafe { Qnil };
+    let mut tail = list;
+
+    while CONSP(tail) {
+        let next = unsafe { (*XCONS(tail)).cdr };
+        XSETCDR(tail, prev);
+        prev = tail;
+        tail = next;
+    }
+
+    prev
+}
+
+lazy_static! {
+    pub static ref Snreverse: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fnreverse as *const libc::c_void),
+        min_args: 1,
+        max_args: 1,
+        symbol_name: ("nreverse\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Reverse order of items in a list, or do nothing if LIST is nil.
+Return the reversed list.  Expects a properly nil-terminated list.
+
+(fn LIST)\0".as_ptr()) as *const c_char,
+    };
+}
+
+/// Destructively concatenate NARGS/ARGS by splicing the cdr of each
+/// non-nil argument's last cons onto the start of the next argument.
+/// Only the final argument is left untouched, and need not be a list.
+#[no_mangle]
+pub extern "C" fn Fnconc(nargs: libc::ptrdiff_t, args: *mut LispObject) -> LispObject {
+    let mut val = unsafe { Qnil };
+    // Last cons cell spliced so far, whose cdr still needs to be
+    // pointed at the next non-nil argument; `Qnil` once none has been
+    // spliced yet, so that nil arguments in between stay transparent.
+    let mut splice = unsafe { Qnil };
+
+    for i in 0..nargs {
+        let arg = unsafe { *args.offset(i as isize) };
+
+        if i == nargs - 1 {
+            // The last argument need not be a list.
+            if !NILP(splice) {
+                XSETCDR(splice, arg);
+            } else if NILP(val) {
+                val = arg;
+            }
+            break;
+        }
+
+        if NILP(arg) {
+            continue;
+        }
+
+        unsafe {
+            CHECK_TYPE(listp(arg), Qlistp, arg);
+        }
+
+        if NILP(val) {
+            val = arg;
+        }
+        if !NILP(splice) {
+            XSETCDR(splice, arg);
+        }
+
+        let mut tail = arg;
+        while CONSP(unsafe { (*XCONS(tail)).cdr }) {
+            tail = unsafe { (*XCONS(tail)).cdr };
+        }
+        splice = tail;
+    }
+
+    val
+}
+
+lazy_static! {
+    pub static ref Snconc: LispSubr = LispSubr {
+        header: VectorLikeHeader {
+            size: ((PvecType::PVEC_SUBR as libc::c_int) <<
+                   PSEUDOVECTOR_AREA_BITS) as libc::ptrdiff_t,
+        },
+        function: (Fnconc as *const libc::c_void),
+        min_args: 0,
+        max_args: -2,
+        symbol_name: ("nconc\0".as_ptr()) as *const c_char,
+        intspec: ptr::null(),
+        doc: ("Concatenate any number of lists by altering them.
+Only the last argument is not altered, and need not be a list.
+
+(fn &rest LISTS)\0".as_ptr()) as *const c_char,
+    };
+}